@@ -7,9 +7,11 @@
 //! - CatalogRoot: Global catalog metadata (admin, counts)
 //! - CatalogPage: Pages of cartridge entries for discovery
 //! - CartridgeManifest: Metadata for each cartridge
-//! - CartridgeChunk: Raw bytes for cartridge data chunks
+//! - ManifestChunkMap: Paged, ordered list of the content hashes that make up a cartridge
+//! - CartridgeChunk: Content-addressed, refcounted bytes for cartridge data chunks
 
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hash;
 
 declare_id!("iXBRbJjLtohupYmSDz3diKTVz2wU8NXe4gezFsSNcy1");
 
@@ -22,21 +24,75 @@ pub const MAX_CARTRIDGE_SIZE: u64 = 6 * 1024 * 1024;
 /// With 800 byte chunks, a 6MB file requires ~7680 chunks.
 pub const DEFAULT_CHUNK_SIZE: u32 = 800;
 
+/// Upper bound on a content-defined chunk (FastCDC `MaxSize`, 64 KiB).
+/// The client cuts cartridges with FastCDC and forces a cut at this size; the
+/// program only stores the resulting variable-length bytes, so every
+/// `CartridgeChunk.data` buffer is sized to hold the largest possible chunk.
+pub const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Maximum bytes accepted per `append_chunk_bytes` call.
+///
+/// Solana transactions are limited to ~1232 bytes total, so a chunk up to
+/// `MAX_CHUNK_SIZE` cannot be submitted in one instruction. The client uploads
+/// it in sequential slices bounded by this size (same transaction-overhead
+/// budget as the baseline fixed-size `DEFAULT_CHUNK_SIZE`) and then calls
+/// `write_or_ref_chunk` to verify and commit the assembled bytes.
+pub const MAX_CHUNK_WRITE_LEN: usize = DEFAULT_CHUNK_SIZE as usize;
+
+/// Size in bytes of the manifest's written-chunk bitmap.
+///
+/// Holds one bit per chunk for the worst case of the smallest (800-byte) chunks
+/// filling a full `MAX_CARTRIDGE_SIZE` cartridge (~7865 bits → ~984 bytes).
+/// Content-defined chunking produces far fewer chunks, so the bitmap is always
+/// large enough to index every `num_chunks` bit.
+pub const CHUNK_BITMAP_LEN: usize =
+    (((MAX_CARTRIDGE_SIZE as usize) + (DEFAULT_CHUNK_SIZE as usize) - 1) / (DEFAULT_CHUNK_SIZE as usize) + 7) / 8;
+
+/// Number of chunk hashes stored inline in a single `ManifestChunkMap` page.
+/// A 6MB cartridge chunked at the 8 KiB FastCDC average yields ~768 hashes,
+/// which fit in two pages; the largest `num_chunks` that `create_manifest`
+/// accepts (`CHUNK_BITMAP_LEN * 8` = 7872) spans 16 pages. Each page account
+/// stays well under the 10MB account limit.
+pub const HASHES_PER_MAP_PAGE: usize = 512;
+
 /// Maximum entries per catalog page
 /// With zero-copy, we can safely have more entries per page
 pub const ENTRIES_PER_PAGE: usize = 16;
 
 /// Size of a catalog entry (fixed for predictable sizing)
-pub const CATALOG_ENTRY_SIZE: usize = 32 + 32 + 8 + 32 + 8 + 1 + 7; // 120 bytes with padding
+pub const CATALOG_ENTRY_SIZE: usize = 32 + 32 + 8 + 8 + 32 + 8 + 1 + 1 + 6; // 128 bytes with padding
 
 /// Maximum metadata length
 pub const MAX_METADATA_LEN: usize = 256;
 
+/// Catalog entry / manifest flag: the cartridge has been retired.
+pub const FLAG_RETIRED: u8 = 0x01;
+
+/// Compression codec applied to the stored cartridge bytes.
+/// `NONE` means the chunks are a raw ZIP; the others are a compressed stream the
+/// reader must inflate after fetching all chunks.
+pub const CODEC_NONE: u8 = 0;
+pub const CODEC_DEFLATE: u8 = 1;
+pub const CODEC_ZSTD: u8 = 2;
+
+/// Number of `(cartridge_id, manifest_pubkey)` slots held inline in one
+/// `IndexBucket`. Also the hard ceiling on the linear-probe window.
+pub const SLOTS_PER_BUCKET: usize = 16;
+
+/// Default index sizing, installed at `initialize_catalog` time.
+/// `2^8 = 256` buckets with a 16-slot probe window covers the initial catalog;
+/// the admin doubles capacity with `grow_index` once buckets start returning
+/// `IndexFull`.
+pub const DEFAULT_NUM_BUCKETS_POW2: u8 = 8;
+pub const DEFAULT_MAX_SEARCH: u8 = 16;
+
 /// Seeds for PDA derivation
 pub const CATALOG_ROOT_SEED: &[u8] = b"catalog_root";
 pub const CATALOG_PAGE_SEED: &[u8] = b"catalog_page";
 pub const MANIFEST_SEED: &[u8] = b"manifest";
 pub const CHUNK_SEED: &[u8] = b"chunk";
+pub const CHUNK_MAP_SEED: &[u8] = b"chunk_map";
+pub const INDEX_BUCKET_SEED: &[u8] = b"index_bucket";
 
 #[program]
 pub mod cartridge_storage {
@@ -50,8 +106,10 @@ pub mod cartridge_storage {
         catalog_root.total_cartridges = 0;
         catalog_root.page_count = 0;
         catalog_root.latest_page_index = 0;
+        catalog_root.num_buckets_pow2 = DEFAULT_NUM_BUCKETS_POW2;
+        catalog_root.max_search = DEFAULT_MAX_SEARCH;
         catalog_root.bump = ctx.bumps.catalog_root;
-        
+
         msg!("Catalog initialized with admin: {}", catalog_root.admin);
         Ok(())
     }
@@ -79,28 +137,57 @@ pub mod cartridge_storage {
     }
 
     /// Create a cartridge manifest. This reserves the cartridge ID.
+    ///
+    /// `num_chunks` is supplied by the client, not derived on-chain: chunks are
+    /// cut with FastCDC into variable-length, content-defined pieces, so there is
+    /// no fixed chunk size this program could divide `zip_size` by to recover the
+    /// real count. The client already knows it, having run the chunker.
     pub fn create_manifest(
         ctx: Context<CreateManifest>,
         cartridge_id: [u8; 32],
         zip_size: u64,
-        chunk_size: u32,
+        num_chunks: u32,
         sha256: [u8; 32],
+        merkle_root: [u8; 32],
+        codec: u8,
+        uncompressed_size: u64,
         metadata: Vec<u8>,
     ) -> Result<()> {
         require!(zip_size > 0, CartridgeError::InvalidSize);
         require!(zip_size <= MAX_CARTRIDGE_SIZE, CartridgeError::CartridgeTooLarge);
-        require!(chunk_size > 0 && chunk_size <= DEFAULT_CHUNK_SIZE, CartridgeError::InvalidChunkSize);
         require!(metadata.len() <= MAX_METADATA_LEN, CartridgeError::MetadataTooLarge);
-        
-        let num_chunks = ((zip_size as u32) + chunk_size - 1) / chunk_size;
-        
+
+        // Validate the codec and decompressed-size accounting. For compressed
+        // codecs the decompressed size must be at least the stored size, and it
+        // must never exceed MAX_CARTRIDGE_SIZE so a tiny upload cannot expand
+        // into a decompression bomb on readers.
+        require!(
+            matches!(codec, CODEC_NONE | CODEC_DEFLATE | CODEC_ZSTD),
+            CartridgeError::InvalidCodec
+        );
+        if codec == CODEC_NONE {
+            require!(uncompressed_size == zip_size, CartridgeError::InvalidSize);
+        } else {
+            require!(uncompressed_size >= zip_size, CartridgeError::InvalidSize);
+        }
+        require!(uncompressed_size <= MAX_CARTRIDGE_SIZE, CartridgeError::CartridgeTooLarge);
+
+        // The completeness bitmap is sized for the 800-byte-chunk worst case;
+        // reject chunk counts that would overflow it.
+        require!(
+            num_chunks > 0 && (num_chunks as usize) <= CHUNK_BITMAP_LEN * 8,
+            CartridgeError::InvalidChunkSize
+        );
+
         // Zero-copy account: load_init for new accounts
         let mut manifest = ctx.accounts.manifest.load_init()?;
         manifest.cartridge_id = cartridge_id;
         manifest.zip_size = zip_size;
-        manifest.chunk_size = chunk_size;
         manifest.num_chunks = num_chunks;
         manifest.sha256 = sha256;
+        manifest.merkle_root = merkle_root;
+        manifest.codec = codec;
+        manifest.uncompressed_size = uncompressed_size;
         manifest.finalized = 0; // false
         manifest.created_slot = Clock::get()?.slot;
         manifest.publisher = ctx.accounts.publisher.key();
@@ -115,54 +202,163 @@ pub mod cartridge_storage {
         Ok(())
     }
 
-    /// Write data to a chunk account. The chunk account must be pre-allocated.
-    pub fn write_chunk(
-        ctx: Context<WriteChunk>,
-        cartridge_id: [u8; 32],
-        chunk_index: u32,
+    /// Append a slice of a content-defined chunk's bytes, ahead of `write_or_ref_chunk`.
+    ///
+    /// `MAX_CHUNK_SIZE` (64 KiB) chunks cannot be submitted in a single Solana
+    /// transaction (~1232 byte limit), so the client uploads a new chunk's bytes
+    /// across several calls to this instruction, each bounded by
+    /// `MAX_CHUNK_WRITE_LEN` and written at the sequential `offset` it left off
+    /// at. Once every byte has been appended, the client calls
+    /// `write_or_ref_chunk` to verify the assembled buffer and commit it.
+    ///
+    /// Only needed the first time a given `chunk_hash` is stored; a dedup
+    /// reference to an already-written chunk skips straight to
+    /// `write_or_ref_chunk` since the bytes already exist on-chain.
+    ///
+    /// The chunk PDA is content-addressed and permissionless to write, like the
+    /// rest of the dedup path, so an `offset` of 0 always (re)starts the
+    /// assembly from scratch. Without this, a chunk account seeded with
+    /// garbage bytes under a predictable `chunk_hash` (e.g. a common shared
+    /// blob) could never be corrected: `write_or_ref_chunk` would permanently
+    /// fail `HashMismatch` and no other instruction could rewind `data_len`.
+    /// Restarting does mean a racing writer can repeatedly reset an in-progress
+    /// upload, but that only costs liveness, not a permanent denial.
+    pub fn append_chunk_bytes(
+        ctx: Context<AppendChunkBytes>,
+        _cartridge_id: [u8; 32],
+        _chunk_index: u32,
+        chunk_hash: [u8; 32],
+        offset: u32,
         data: Vec<u8>,
+    ) -> Result<()> {
+        require!(!data.is_empty(), CartridgeError::InvalidChunkSize);
+        require!(data.len() <= MAX_CHUNK_WRITE_LEN, CartridgeError::InvalidChunkSize);
+
+        let mut chunk = ctx.accounts.chunk.load_mut()?;
+        require!(chunk.written == 0, CartridgeError::ChunkAlreadyWritten);
+
+        if offset == 0 {
+            // (Re)start the assembly: bind the account to the hash it will be
+            // verified against at commit time and discard any bytes appended
+            // by a previous, possibly poisoned or abandoned, attempt.
+            chunk.chunk_hash = chunk_hash;
+            chunk.bump = ctx.bumps.chunk;
+            chunk.data_len = 0;
+        } else {
+            // Appends must be sequential and contiguous: `data_len` doubles as
+            // the write cursor while the chunk is still being assembled.
+            require!(offset == chunk.data_len, CartridgeError::InvalidWriteOffset);
+            require!(chunk.chunk_hash == chunk_hash, CartridgeError::HashMismatch);
+        }
+        let end = offset as usize + data.len();
+        require!(end <= MAX_CHUNK_SIZE, CartridgeError::InvalidChunkSize);
+        chunk.data[offset as usize..end].copy_from_slice(&data);
+        chunk.data_len = end as u32;
+
+        msg!("Appended {} bytes to chunk {:?} (total {})", data.len(), chunk_hash, end);
+        Ok(())
+    }
+
+    /// Verify an assembled (or already-deduplicated) chunk and commit it to a
+    /// cartridge, or bump its refcount if it already exists.
+    ///
+    /// Chunks are content-addressed: the PDA is seeded by the chunk's own sha256,
+    /// so the same bytes published by two different cartridges (shared BIOS blobs,
+    /// repeated ZIP headers, re-published ROM versions) land on the same account
+    /// and are stored on-chain only once. The first writer pays rent and stores
+    /// the bytes (uploaded beforehand via `append_chunk_bytes`); every subsequent
+    /// reference just increments `refcount`.
+    ///
+    /// The chunk's position in the cartridge is recorded in the appropriate
+    /// `ManifestChunkMap` page so readers can reassemble the ordered stream.
+    pub fn write_or_ref_chunk(
+        ctx: Context<WriteOrRefChunk>,
+        _cartridge_id: [u8; 32],
+        chunk_index: u32,
+        chunk_hash: [u8; 32],
+        merkle_proof: Vec<[u8; 32]>,
     ) -> Result<()> {
         // Load manifest as read-only
         let manifest = ctx.accounts.manifest.load()?;
-        
         require!(manifest.finalized == 0, CartridgeError::CartridgeFinalized);
         require!(chunk_index < manifest.num_chunks, CartridgeError::InvalidChunkIndex);
-        
-        // Store values before dropping borrow
-        let manifest_chunk_size = manifest.chunk_size;
-        let manifest_num_chunks = manifest.num_chunks;
-        let manifest_zip_size = manifest.zip_size;
+        let merkle_root = manifest.merkle_root;
         drop(manifest);
-        
-        // Zero-copy account: load_init for new accounts
-        let mut chunk = ctx.accounts.chunk.load_init()?;
-        
-        require!(chunk.written == 0, CartridgeError::ChunkAlreadyWritten);
-        
-        // Validate data size
-        let expected_size = if chunk_index == manifest_num_chunks - 1 {
-            // Last chunk may be smaller
-            let remainder = manifest_zip_size as u32 % manifest_chunk_size;
-            if remainder == 0 { manifest_chunk_size } else { remainder }
+
+        // Idempotent on retries: if this ordered slot already points at this
+        // exact hash, a previous (possibly dropped-on-the-wire) call already
+        // recorded it, so don't bump the refcount a second time. Otherwise the
+        // slot must be unset: replacing a different hash here without first
+        // releasing its reference would leak that chunk's rent forever (its
+        // refcount could never reach zero) and strand its account, since the
+        // old hash would no longer be reachable from this cartridge's map.
+        {
+            let map = ctx.accounts.chunk_map.load_mut()?;
+            let slot = (chunk_index as usize) % HASHES_PER_MAP_PAGE;
+            let existing = map.hashes[slot];
+            if existing == chunk_hash && chunk_hash != [0u8; 32] {
+                msg!("Chunk {} already recorded; no-op", chunk_index);
+                return Ok(());
+            }
+            require!(existing == [0u8; 32], CartridgeError::ChunkIndexAlreadyAssigned);
+        }
+
+        // Zero-copy account: the bytes were already assembled by
+        // `append_chunk_bytes`, so load it with load_mut and use `written` to
+        // tell a not-yet-committed upload (0) from a stored chunk (1).
+        let mut chunk = ctx.accounts.chunk.load_mut()?;
+
+        // Every reference (first write or dedup) must prove the stored bytes
+        // are a leaf of this cartridge's own Merkle tree at `chunk_index`.
+        let len = chunk.data_len as usize;
+        require!(len > 0, CartridgeError::InvalidChunkSize);
+        let bytes = &chunk.data[..len];
+        let computed_root = merkle_root_from_proof(chunk_index, bytes, &merkle_proof);
+        require!(computed_root == merkle_root, CartridgeError::HashMismatch);
+
+        if chunk.written == 0 {
+            // Bytes were assembled via `append_chunk_bytes`; also verify them
+            // against the content-address the caller named, so a corrupt or
+            // incomplete upload can never be stored.
+            require!(hash(bytes).to_bytes() == chunk_hash, CartridgeError::HashMismatch);
+
+            chunk.refcount = 1;
+            chunk.written = 1; // true
+
+            msg!("Stored chunk {:?} ({} bytes)", chunk_hash, len);
         } else {
-            manifest_chunk_size
-        };
-        
-        require!(
-            data.len() as u32 == expected_size,
-            CartridgeError::InvalidChunkSize
-        );
-        
-        chunk.cartridge_id = cartridge_id;
-        chunk.chunk_index = chunk_index;
-        chunk.data_len = data.len() as u32;
-        chunk.written = 1; // true
-        chunk.bump = ctx.bumps.chunk;
-        
-        // Write data to the data field
-        chunk.data[..data.len()].copy_from_slice(&data);
-        
-        msg!("Wrote chunk {} for cartridge (size: {} bytes)", chunk_index, data.len());
+            // Bytes already verified when first written; this cartridge only
+            // needed the Merkle check above.
+
+            chunk.refcount = chunk.refcount
+                .checked_add(1)
+                .ok_or(CartridgeError::RefcountOverflow)?;
+
+            msg!("Referenced existing chunk {:?} (refcount: {})", chunk_hash, chunk.refcount);
+        }
+        drop(chunk);
+
+        // Mark the chunk present in the manifest's completeness bitmap.
+        {
+            let mut manifest = ctx.accounts.manifest.load_mut()?;
+            let byte = (chunk_index / 8) as usize;
+            let bit = (chunk_index % 8) as u8;
+            manifest.chunk_bitmap[byte] |= 1 << bit;
+        }
+
+        // Record the hash at its ordered slot in the manifest's chunk map.
+        let slot = (chunk_index as usize) % HASHES_PER_MAP_PAGE;
+        let mut map = ctx.accounts.chunk_map.load_mut()?;
+        if map.hash_count == 0 {
+            map.cartridge_id = _cartridge_id;
+            map.page_index = chunk_index / HASHES_PER_MAP_PAGE as u32;
+            map.bump = ctx.bumps.chunk_map;
+        }
+        map.hashes[slot] = chunk_hash;
+        if (slot as u32) + 1 > map.hash_count {
+            map.hash_count = slot as u32 + 1;
+        }
+
         Ok(())
     }
 
@@ -172,6 +368,7 @@ pub mod cartridge_storage {
         ctx: Context<FinalizeCartridge>,
         cartridge_id: [u8; 32],
         _page_index: u32,
+        bucket_index: u32,
     ) -> Result<()> {
         let catalog_root = &mut ctx.accounts.catalog_root;
         
@@ -179,12 +376,21 @@ pub mod cartridge_storage {
         let mut manifest = ctx.accounts.manifest.load_mut()?;
         
         require!(manifest.finalized == 0, CartridgeError::CartridgeFinalized);
-        
+
+        // Refuse to publish a cartridge with holes: every chunk below num_chunks
+        // must have been written.
+        require!(
+            manifest.first_missing_chunk().is_none(),
+            CartridgeError::IncompleteCartridge
+        );
+
         // Get manifest key
         let manifest_pubkey = ctx.accounts.manifest.key();
         
         // Store values before marking as finalized
         let zip_size = manifest.zip_size;
+        let uncompressed_size = manifest.uncompressed_size;
+        let codec = manifest.codec;
         let sha256 = manifest.sha256;
         let created_slot = manifest.created_slot;
         
@@ -210,20 +416,173 @@ pub mod cartridge_storage {
             cartridge_id,
             manifest_pubkey,
             zip_size,
+            uncompressed_size,
             sha256,
             created_slot,
+            codec,
             flags: 0,
-            _padding: [0u8; 7],
+            _padding: [0u8; 6],
         };
         catalog_page.entry_count += 1;
         
         // Update catalog root
         catalog_root.total_cartridges += 1;
-        
+
+        // Insert into the hash-bucketed secondary index so discovery can resolve
+        // this cartridge in one account read instead of paging the catalog.
+        let num_buckets = 1u64 << catalog_root.num_buckets_pow2;
+        let id_low = u64::from_le_bytes(cartridge_id[..8].try_into().unwrap());
+        let home = (id_low & (num_buckets - 1)) as u32;
+        require!(bucket_index == home, CartridgeError::InvalidBucketIndex);
+
+        let max_search = catalog_root.max_search as usize;
+        let mut bucket = ctx.accounts.index_bucket.load_mut()?;
+        if bucket.bump == 0 {
+            bucket.bucket_index = home;
+            bucket.generation = catalog_root.num_buckets_pow2;
+            bucket.bump = ctx.bumps.index_bucket;
+        }
+
+        // Linear probe across the home bucket's slot window for the first free
+        // slot; the probe does not continue into the following buckets.
+        let mut placed = false;
+        for i in 0..max_search.min(SLOTS_PER_BUCKET) {
+            if bucket.slots[i].manifest_pubkey == Pubkey::default() {
+                bucket.slots[i] = IndexSlot { cartridge_id, manifest_pubkey };
+                if (i as u32) + 1 > bucket.slot_count {
+                    bucket.slot_count = i as u32 + 1;
+                }
+                placed = true;
+                break;
+            }
+        }
+        require!(placed, CartridgeError::IndexFull);
+
         msg!("Finalized cartridge: {:?}, total: {}", cartridge_id, catalog_root.total_cartridges);
         Ok(())
     }
 
+    /// Double the secondary index capacity (admin only).
+    ///
+    /// Bumps `num_buckets_pow2`, which widens the bucket mask and starts a fresh
+    /// generation of `IndexBucket` PDAs (seeds carry the generation). Buckets in
+    /// the new generation are created lazily on the next `finalize_cartridge`.
+    /// Entries indexed under an earlier generation are not copied forward and
+    /// stay only in that generation's buckets, so readers should fall back to
+    /// the catalog pages for IDs missing from the current generation's bucket.
+    pub fn grow_index(ctx: Context<GrowIndex>) -> Result<()> {
+        let catalog_root = &mut ctx.accounts.catalog_root;
+        require!(catalog_root.num_buckets_pow2 < 31, CartridgeError::IndexFull);
+        catalog_root.num_buckets_pow2 += 1;
+        msg!("Grew index to 2^{} buckets", catalog_root.num_buckets_pow2);
+        Ok(())
+    }
+
+    /// Log the first missing chunk of an in-progress cartridge, as a resume hint.
+    ///
+    /// Clients normally read `chunk_bitmap` directly to compute the full set of
+    /// unset indices; this view is a cheap on-chain probe for the next chunk an
+    /// interrupted upload should re-send.
+    pub fn missing_chunks(ctx: Context<MissingChunks>, _cartridge_id: [u8; 32]) -> Result<()> {
+        let manifest = ctx.accounts.manifest.load()?;
+        match manifest.first_missing_chunk() {
+            Some(index) => msg!("First missing chunk: {}", index),
+            None => msg!("Cartridge is complete"),
+        }
+        Ok(())
+    }
+
+    /// Retire a cartridge (publisher or admin).
+    ///
+    /// Sets the retired flag on both the manifest and its catalog-page entry and
+    /// decrements the live cartridge count. Retiring does not itself free rent;
+    /// the publisher then calls `close_chunks` to reclaim the lamports locked in
+    /// the cartridge's chunk accounts.
+    pub fn retire_cartridge(
+        ctx: Context<RetireCartridge>,
+        cartridge_id: [u8; 32],
+        _page_index: u32,
+    ) -> Result<()> {
+        let mut manifest = ctx.accounts.manifest.load_mut()?;
+        require!(manifest.finalized == 1, CartridgeError::CartridgeNotFinalized);
+        require!(manifest.flags & FLAG_RETIRED == 0, CartridgeError::AlreadyRetired);
+        manifest.flags |= FLAG_RETIRED;
+        drop(manifest);
+
+        // Mirror the flag into the matching catalog-page entry.
+        let mut catalog_page = ctx.accounts.catalog_page.load_mut()?;
+        let count = catalog_page.entry_count as usize;
+        let mut found = false;
+        for i in 0..count {
+            if catalog_page.entries[i].cartridge_id == cartridge_id {
+                catalog_page.entries[i].flags |= FLAG_RETIRED;
+                found = true;
+                break;
+            }
+        }
+        require!(found, CartridgeError::EntryNotFound);
+        drop(catalog_page);
+
+        let catalog_root = &mut ctx.accounts.catalog_root;
+        catalog_root.total_cartridges = catalog_root.total_cartridges.saturating_sub(1);
+
+        msg!("Retired cartridge: {:?}", cartridge_id);
+        Ok(())
+    }
+
+    /// Close a batch of a retired cartridge's chunk accounts and reclaim rent.
+    ///
+    /// The chunk accounts are passed via `remaining_accounts` and must all belong
+    /// to the supplied `chunk_map` page of the retired cartridge. Each chunk's
+    /// hash is located in the map and that slot is zeroed so the same chunk can
+    /// never be released twice; the refcount is then decremented and accounts
+    /// whose refcount reaches zero are closed and their lamports returned to the
+    /// publisher. Shared (deduplicated) chunks survive until the last referencing
+    /// cartridge releases them.
+    pub fn close_chunks(
+        ctx: Context<CloseChunks>,
+        _cartridge_id: [u8; 32],
+        _page_index: u32,
+    ) -> Result<()> {
+        {
+            let manifest = ctx.accounts.manifest.load()?;
+            require!(manifest.flags & FLAG_RETIRED != 0, CartridgeError::NotRetired);
+        }
+
+        let payer = ctx.accounts.payer.to_account_info();
+        let mut map = ctx.accounts.chunk_map.load_mut()?;
+        let count = map.hash_count as usize;
+        for acct in ctx.remaining_accounts.iter() {
+            let loader = AccountLoader::<CartridgeChunk>::try_from(acct)?;
+            let close = {
+                let mut chunk = loader.load_mut()?;
+                // Confirm the account is the content-addressed PDA it claims to be,
+                // using the stored bump to avoid a full bump search.
+                let expected = Pubkey::create_program_address(
+                    &[CHUNK_SEED, &chunk.chunk_hash, &[chunk.bump]],
+                    ctx.program_id,
+                )
+                .map_err(|_| CartridgeError::Unauthorized)?;
+                require_keys_eq!(expected, acct.key(), CartridgeError::Unauthorized);
+
+                // Confirm the chunk belongs to this cartridge and has not already
+                // been released, then consume the map slot.
+                let slot = map.hashes[..count]
+                    .iter()
+                    .position(|h| *h == chunk.chunk_hash)
+                    .ok_or(CartridgeError::ChunkNotInCartridge)?;
+                map.hashes[slot] = [0u8; 32];
+
+                chunk.refcount = chunk.refcount.saturating_sub(1);
+                chunk.refcount == 0
+            };
+            if close {
+                close_account(acct, &payer)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Update admin (admin only)
     pub fn update_admin(ctx: Context<UpdateAdmin>, new_admin: Pubkey) -> Result<()> {
         let catalog_root = &mut ctx.accounts.catalog_root;
@@ -233,6 +592,60 @@ pub mod cartridge_storage {
     }
 }
 
+// ============================================================================
+// Helpers
+// ============================================================================
+
+/// Recompute a Merkle root from a leaf's bytes and its sibling path.
+///
+/// Leaves are `sha256(0x00 || chunk_bytes)` and internal nodes are
+/// `sha256(0x01 || left || right)` (domain-separated to prevent second-preimage
+/// attacks). The siblings in `proof` are folded in from the leaf upward, with
+/// the bits of `index` selecting whether the running hash is the left or right
+/// input at each level.
+fn merkle_root_from_proof(index: u32, bytes: &[u8], proof: &[[u8; 32]]) -> [u8; 32] {
+    let mut leaf_buf = Vec::with_capacity(1 + bytes.len());
+    leaf_buf.push(0x00);
+    leaf_buf.extend_from_slice(bytes);
+    let mut node = hash(&leaf_buf).to_bytes();
+
+    let mut idx = index;
+    let mut buf = [0u8; 65];
+    buf[0] = 0x01;
+    for sibling in proof {
+        if idx & 1 == 0 {
+            buf[1..33].copy_from_slice(&node);
+            buf[33..65].copy_from_slice(sibling);
+        } else {
+            buf[1..33].copy_from_slice(sibling);
+            buf[33..65].copy_from_slice(&node);
+        }
+        node = hash(&buf).to_bytes();
+        idx >>= 1;
+    }
+    node
+}
+
+/// Close a zero-copy account by draining its lamports to `dest` and resetting it.
+///
+/// Anchor's `close` constraint is unconditional, but content-addressed chunks
+/// must only be closed once their refcount reaches zero, so the close is done
+/// manually here after that check.
+fn close_account<'info>(
+    account: &AccountInfo<'info>,
+    dest: &AccountInfo<'info>,
+) -> Result<()> {
+    let lamports = account.lamports();
+    **dest.try_borrow_mut_lamports()? += lamports;
+    **account.try_borrow_mut_lamports()? = 0;
+
+    // Stamp the Anchor closed-account sentinel so the PDA cannot be revived or
+    // reinitialized within the same transaction.
+    let mut data = account.try_borrow_mut_data()?;
+    data[..8].copy_from_slice(&anchor_lang::__private::CLOSED_ACCOUNT_DISCRIMINATOR);
+    Ok(())
+}
+
 // ============================================================================
 // Account Structures
 // ============================================================================
@@ -253,6 +666,11 @@ pub struct CatalogRoot {
     pub latest_page_index: u32,
     /// PDA bump
     pub bump: u8,
+    /// log2 of the number of secondary-index buckets (also the current generation).
+    /// Appended after `bump` so existing catalog accounts keep their layout.
+    pub num_buckets_pow2: u8,
+    /// Maximum linear-probe window before an insert returns `IndexFull`
+    pub max_search: u8,
 }
 
 impl CatalogRoot {
@@ -262,8 +680,10 @@ impl CatalogRoot {
         8 +     // total_cartridges
         4 +     // page_count
         4 +     // latest_page_index
+        1 +     // num_buckets_pow2
+        1 +     // max_search
         1 +     // bump
-        16;     // padding for future fields
+        14;     // padding for future fields
 }
 
 /// Single catalog entry
@@ -274,16 +694,20 @@ pub struct CatalogEntry {
     pub cartridge_id: [u8; 32],
     /// Pubkey of the manifest account
     pub manifest_pubkey: Pubkey,
-    /// Size of the ZIP file in bytes
+    /// Size of the stored (possibly compressed) bytes
     pub zip_size: u64,
+    /// Size of the content after decompression (true content size for discovery)
+    pub uncompressed_size: u64,
     /// SHA256 hash of the ZIP file
     pub sha256: [u8; 32],
     /// Slot when the cartridge was created
     pub created_slot: u64,
+    /// Compression codec of the stored bytes (see CODEC_* constants)
+    pub codec: u8,
     /// Flags (e.g., 0x01 = retired)
     pub flags: u8,
     /// Padding for alignment
-    pub _padding: [u8; 7],
+    pub _padding: [u8; 6],
 }
 
 /// Catalog page - contains entries for discovery (zero-copy for large arrays)
@@ -311,24 +735,76 @@ impl CatalogPage {
         (CATALOG_ENTRY_SIZE * ENTRIES_PER_PAGE); // entries
 }
 
+/// Single secondary-index slot mapping a cartridge ID to its manifest.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+#[repr(C)]
+pub struct IndexSlot {
+    /// Content-addressed cartridge ID
+    pub cartridge_id: [u8; 32],
+    /// Pubkey of the manifest account (default/zero means the slot is empty)
+    pub manifest_pubkey: Pubkey,
+}
+
+/// Hash-bucketed secondary index entry (zero-copy for the slot array).
+///
+/// Seeded by `(generation, bucket_index)` where `bucket_index` is the low bits
+/// of the cartridge ID, so a reader derives the PDA directly from an ID and
+/// resolves the manifest in one account read. Inserts probe only this bucket's
+/// own slots; an entry is never placed in a neighbouring bucket.
+#[account(zero_copy)]
+#[repr(C)]
+pub struct IndexBucket {
+    /// Bucket index within its generation
+    pub bucket_index: u32,
+    /// Highest filled slot + 1
+    pub slot_count: u32,
+    /// Generation this bucket belongs to (equals `num_buckets_pow2` at creation)
+    pub generation: u8,
+    /// PDA bump
+    pub bump: u8,
+    /// Padding for alignment
+    pub _padding: [u8; 6],
+    /// Probe window of slots
+    pub slots: [IndexSlot; SLOTS_PER_BUCKET],
+}
+
+impl IndexBucket {
+    pub const LEN: usize = 8 + // discriminator
+        4 +     // bucket_index
+        4 +     // slot_count
+        1 +     // generation
+        1 +     // bump
+        6 +     // padding
+        (64 * SLOTS_PER_BUCKET); // slots (32 + 32 each)
+}
+
 /// Cartridge manifest - metadata for a cartridge (zero-copy due to metadata array)
 #[account(zero_copy)]
 #[repr(C)]
 pub struct CartridgeManifest {
     /// Content-addressed ID (sha256 of ZIP bytes)
     pub cartridge_id: [u8; 32],
-    /// Total size of the ZIP file
+    /// Total size of the stored (possibly compressed) bytes
     pub zip_size: u64,
-    /// Size of each chunk
-    pub chunk_size: u32,
-    /// Number of chunks
+    /// Size of the content after decompression (equals `zip_size` when `codec` is NONE)
+    pub uncompressed_size: u64,
+    /// Number of content-defined chunks the client cut this cartridge into.
+    /// Supplied by the client at `create_manifest` time (it ran FastCDC and knows
+    /// the real cut count); not derivable on-chain since chunks are variable length.
     pub num_chunks: u32,
     /// SHA256 hash of the ZIP file
     pub sha256: [u8; 32],
+    /// Root of the binary sha256 Merkle tree over the ordered chunks.
+    /// Lets light clients fetch and verify any single chunk in isolation.
+    pub merkle_root: [u8; 32],
     /// Whether the cartridge is finalized (locked) - 0 = false, 1 = true
     pub finalized: u8,
+    /// Status flags (mirrors `CatalogEntry.flags`, e.g. 0x01 = retired)
+    pub flags: u8,
+    /// Compression codec of the stored bytes (see CODEC_* constants)
+    pub codec: u8,
     /// Padding for alignment
-    pub _finalized_padding: [u8; 7],
+    pub _finalized_padding: [u8; 1],
     /// Slot when the manifest was created
     pub created_slot: u64,
     /// Publisher pubkey
@@ -341,35 +817,90 @@ pub struct CartridgeManifest {
     pub _metadata_padding: [u8; 5],
     /// Optional metadata (JSON, etc.)
     pub metadata: [u8; MAX_METADATA_LEN],
+    /// One bit per chunk index; set when the chunk has been written.
+    /// Lets `finalize_cartridge` reject cartridges with holes and lets an
+    /// interrupted upload resume by re-sending only the unset indices.
+    pub chunk_bitmap: [u8; CHUNK_BITMAP_LEN],
 }
 
 impl CartridgeManifest {
+    /// Returns true if the chunk at `index` has been written.
+    pub fn is_chunk_written(&self, index: u32) -> bool {
+        let byte = (index / 8) as usize;
+        let bit = (index % 8) as u8;
+        byte < CHUNK_BITMAP_LEN && (self.chunk_bitmap[byte] & (1 << bit)) != 0
+    }
+
+    /// Returns the first chunk index below `num_chunks` that has not been
+    /// written, or `None` if the cartridge is complete.
+    pub fn first_missing_chunk(&self) -> Option<u32> {
+        (0..self.num_chunks).find(|&i| !self.is_chunk_written(i))
+    }
+
     pub const LEN: usize = 8 + // discriminator
         32 +    // cartridge_id
         8 +     // zip_size
-        4 +     // chunk_size
+        8 +     // uncompressed_size
         4 +     // num_chunks
         32 +    // sha256
+        32 +    // merkle_root
         1 +     // finalized
-        7 +     // finalized_padding
+        1 +     // flags
+        1 +     // codec
+        1 +     // finalized_padding
         8 +     // created_slot
         32 +    // publisher
         2 +     // metadata_len
         1 +     // bump
         5 +     // metadata_padding
         MAX_METADATA_LEN + // metadata
+        CHUNK_BITMAP_LEN + // chunk_bitmap
         16;     // extra padding
 }
 
-/// Cartridge chunk - raw bytes for a chunk (zero-copy for large data)
+/// Paged, ordered list of the content hashes that make up a cartridge.
+///
+/// A manifest can reference thousands of chunks, which will not fit inline in
+/// `CartridgeManifest`, so the ordered hash list is spilled across a sequence of
+/// these pages seeded by `(cartridge_id, page_index)`.
 #[account(zero_copy)]
 #[repr(C)]
-pub struct CartridgeChunk {
-    /// Cartridge ID this chunk belongs to
+pub struct ManifestChunkMap {
+    /// Cartridge this page belongs to
     pub cartridge_id: [u8; 32],
-    /// Chunk index (0-based)
-    pub chunk_index: u32,
-    /// Length of data in this chunk
+    /// 0-based page index within the manifest's hash list
+    pub page_index: u32,
+    /// Highest filled slot + 1 within this page
+    pub hash_count: u32,
+    /// PDA bump
+    pub bump: u8,
+    /// Padding for alignment
+    pub _padding: [u8; 7],
+    /// Ordered chunk hashes; slot `i` is chunk `page_index * HASHES_PER_MAP_PAGE + i`
+    pub hashes: [[u8; 32]; HASHES_PER_MAP_PAGE],
+}
+
+impl ManifestChunkMap {
+    pub const LEN: usize = 8 + // discriminator
+        32 +    // cartridge_id
+        4 +     // page_index
+        4 +     // hash_count
+        1 +     // bump
+        7 +     // padding
+        (32 * HASHES_PER_MAP_PAGE); // hashes
+}
+
+/// Cartridge chunk - content-addressed, refcounted bytes (zero-copy for large data)
+#[account(zero_copy)]
+#[repr(C)]
+pub struct CartridgeChunk {
+    /// sha256 of the chunk bytes (also the PDA seed)
+    pub chunk_hash: [u8; 32],
+    /// Number of references to this chunk across all cartridges
+    pub refcount: u32,
+    /// While `written == 0`, the number of bytes appended so far (the next
+    /// `append_chunk_bytes` call must start at this offset). Once `written == 1`,
+    /// the final length of valid data in `data`.
     pub data_len: u32,
     /// Whether this chunk has been written - 0 = false, 1 = true
     pub written: u8,
@@ -377,21 +908,21 @@ pub struct CartridgeChunk {
     pub bump: u8,
     /// Padding for alignment
     pub _padding: [u8; 6],
-    /// Raw chunk data (up to DEFAULT_CHUNK_SIZE bytes)
-    pub data: [u8; DEFAULT_CHUNK_SIZE as usize],
+    /// Raw chunk data (variable length, up to MAX_CHUNK_SIZE bytes)
+    pub data: [u8; MAX_CHUNK_SIZE],
 }
 
 impl CartridgeChunk {
     /// Calculate the space needed for a chunk account
-    pub fn space(_data_size: u32) -> usize {
+    pub fn space() -> usize {
         8 +     // discriminator
-        32 +    // cartridge_id
-        4 +     // chunk_index
+        32 +    // chunk_hash
+        4 +     // refcount
         4 +     // data_len
         1 +     // written
         1 +     // bump
         6 +     // padding
-        DEFAULT_CHUNK_SIZE as usize + // data (fixed size for zero-copy)
+        MAX_CHUNK_SIZE + // data (fixed size for zero-copy)
         32      // extra padding
     }
 }
@@ -462,9 +993,31 @@ pub struct CreateManifest<'info> {
 }
 
 #[derive(Accounts)]
-#[instruction(cartridge_id: [u8; 32], chunk_index: u32)]
-pub struct WriteChunk<'info> {
+#[instruction(cartridge_id: [u8; 32], chunk_index: u32, chunk_hash: [u8; 32])]
+pub struct AppendChunkBytes<'info> {
+    // Content-addressed: seeded by the chunk hash so identical bytes shared
+    // across cartridges resolve to one account. `init_if_needed` lets the first
+    // writer allocate it; later appends to the same in-progress chunk reuse it.
+    #[account(
+        init_if_needed,
+        payer = publisher,
+        space = CartridgeChunk::space(),
+        seeds = [CHUNK_SEED, &chunk_hash],
+        bump
+    )]
+    pub chunk: AccountLoader<'info, CartridgeChunk>,
+
+    #[account(mut)]
+    pub publisher: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(cartridge_id: [u8; 32], chunk_index: u32, chunk_hash: [u8; 32])]
+pub struct WriteOrRefChunk<'info> {
     #[account(
+        mut,
         seeds = [MANIFEST_SEED, &cartridge_id],
         bump,
         constraint = {
@@ -473,24 +1026,36 @@ pub struct WriteChunk<'info> {
         } @ CartridgeError::Unauthorized
     )]
     pub manifest: AccountLoader<'info, CartridgeManifest>,
-    
+
+    // Content-addressed: seeded by the chunk hash so identical bytes shared
+    // across cartridges resolve to one account. `init_if_needed` lets the first
+    // writer allocate it and every later reference reuse it.
     #[account(
-        init,
+        init_if_needed,
         payer = publisher,
-        space = CartridgeChunk::space(DEFAULT_CHUNK_SIZE),
-        seeds = [CHUNK_SEED, &cartridge_id, &chunk_index.to_le_bytes()],
+        space = CartridgeChunk::space(),
+        seeds = [CHUNK_SEED, &chunk_hash],
         bump
     )]
     pub chunk: AccountLoader<'info, CartridgeChunk>,
-    
+
+    #[account(
+        init_if_needed,
+        payer = publisher,
+        space = ManifestChunkMap::LEN,
+        seeds = [CHUNK_MAP_SEED, &cartridge_id, &(chunk_index / HASHES_PER_MAP_PAGE as u32).to_le_bytes()],
+        bump
+    )]
+    pub chunk_map: AccountLoader<'info, ManifestChunkMap>,
+
     #[account(mut)]
     pub publisher: Signer<'info>,
-    
+
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-#[instruction(cartridge_id: [u8; 32], page_index: u32)]
+#[instruction(cartridge_id: [u8; 32], page_index: u32, bucket_index: u32)]
 pub struct FinalizeCartridge<'info> {
     #[account(
         mut,
@@ -502,23 +1067,131 @@ pub struct FinalizeCartridge<'info> {
         } @ CartridgeError::Unauthorized
     )]
     pub manifest: AccountLoader<'info, CartridgeManifest>,
-    
+
     #[account(
         mut,
         seeds = [CATALOG_ROOT_SEED],
         bump = catalog_root.bump
     )]
     pub catalog_root: Account<'info, CatalogRoot>,
-    
+
     #[account(
         mut,
         seeds = [CATALOG_PAGE_SEED, &page_index.to_le_bytes()],
         bump
     )]
     pub catalog_page: AccountLoader<'info, CatalogPage>,
-    
+
+    #[account(
+        init_if_needed,
+        payer = publisher,
+        space = IndexBucket::LEN,
+        seeds = [INDEX_BUCKET_SEED, &[catalog_root.num_buckets_pow2], &bucket_index.to_le_bytes()],
+        bump
+    )]
+    pub index_bucket: AccountLoader<'info, IndexBucket>,
+
     #[account(mut)]
     pub publisher: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GrowIndex<'info> {
+    #[account(
+        mut,
+        seeds = [CATALOG_ROOT_SEED],
+        bump = catalog_root.bump,
+        constraint = admin.key() == catalog_root.admin @ CartridgeError::Unauthorized
+    )]
+    pub catalog_root: Account<'info, CatalogRoot>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(cartridge_id: [u8; 32], page_index: u32)]
+pub struct RetireCartridge<'info> {
+    #[account(
+        mut,
+        seeds = [CATALOG_ROOT_SEED],
+        bump = catalog_root.bump
+    )]
+    pub catalog_root: Account<'info, CatalogRoot>,
+
+    #[account(
+        mut,
+        seeds = [MANIFEST_SEED, &cartridge_id],
+        bump,
+        constraint = {
+            let m = manifest.load()?;
+            signer.key() == m.publisher || signer.key() == catalog_root.admin
+        } @ CartridgeError::Unauthorized
+    )]
+    pub manifest: AccountLoader<'info, CartridgeManifest>,
+
+    #[account(
+        mut,
+        seeds = [CATALOG_PAGE_SEED, &page_index.to_le_bytes()],
+        bump
+    )]
+    pub catalog_page: AccountLoader<'info, CatalogPage>,
+
+    pub signer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(cartridge_id: [u8; 32], page_index: u32)]
+pub struct CloseChunks<'info> {
+    #[account(
+        seeds = [CATALOG_ROOT_SEED],
+        bump = catalog_root.bump
+    )]
+    pub catalog_root: Account<'info, CatalogRoot>,
+
+    #[account(
+        seeds = [MANIFEST_SEED, &cartridge_id],
+        bump,
+        constraint = {
+            let m = manifest.load()?;
+            signer.key() == m.publisher || signer.key() == catalog_root.admin
+        } @ CartridgeError::Unauthorized
+    )]
+    pub manifest: AccountLoader<'info, CartridgeManifest>,
+
+    #[account(
+        mut,
+        seeds = [CHUNK_MAP_SEED, &cartridge_id, &page_index.to_le_bytes()],
+        bump = {
+            let cm = chunk_map.load()?;
+            cm.bump
+        }
+    )]
+    pub chunk_map: AccountLoader<'info, ManifestChunkMap>,
+
+    /// CHECK: rent destination for closed chunks; must be the cartridge publisher.
+    #[account(
+        mut,
+        constraint = {
+            let m = manifest.load()?;
+            payer.key() == m.publisher
+        } @ CartridgeError::Unauthorized
+    )]
+    pub payer: UncheckedAccount<'info>,
+
+    pub signer: Signer<'info>,
+    // remaining_accounts: the CartridgeChunk accounts to close.
+}
+
+#[derive(Accounts)]
+#[instruction(cartridge_id: [u8; 32])]
+pub struct MissingChunks<'info> {
+    #[account(
+        seeds = [MANIFEST_SEED, &cartridge_id],
+        bump
+    )]
+    pub manifest: AccountLoader<'info, CartridgeManifest>,
 }
 
 #[derive(Accounts)]
@@ -557,7 +1230,13 @@ pub enum CartridgeError {
     
     #[msg("Chunk has already been written")]
     ChunkAlreadyWritten,
-    
+
+    #[msg("Chunk bytes must be appended sequentially from the current offset")]
+    InvalidWriteOffset,
+
+    #[msg("Chunk index is already assigned to a different chunk hash")]
+    ChunkIndexAlreadyAssigned,
+
     #[msg("Cartridge has already been finalized")]
     CartridgeFinalized,
     
@@ -572,4 +1251,34 @@ pub enum CartridgeError {
     
     #[msg("SHA256 hash mismatch")]
     HashMismatch,
+
+    #[msg("Chunk refcount overflow")]
+    RefcountOverflow,
+
+    #[msg("Secondary index bucket probe window exhausted")]
+    IndexFull,
+
+    #[msg("Bucket index does not match the cartridge's home bucket")]
+    InvalidBucketIndex,
+
+    #[msg("Cartridge has unwritten chunks and cannot be finalized")]
+    IncompleteCartridge,
+
+    #[msg("Cartridge must be finalized before this action")]
+    CartridgeNotFinalized,
+
+    #[msg("Cartridge is already retired")]
+    AlreadyRetired,
+
+    #[msg("Cartridge must be retired before closing its chunks")]
+    NotRetired,
+
+    #[msg("No matching catalog entry found")]
+    EntryNotFound,
+
+    #[msg("Chunk does not belong to this cartridge")]
+    ChunkNotInCartridge,
+
+    #[msg("Unsupported compression codec")]
+    InvalidCodec,
 }